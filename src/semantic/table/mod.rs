@@ -1,3 +1,4 @@
+use std::backtrace::Backtrace;
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -17,12 +18,131 @@ pub use super::pattern::{Pattern, PatternError};
 
 //pub mod disassembly;
 //pub mod execution;
+pub mod model;
+
+/// Stable, machine-readable identifier for a `TableError`, in the same
+/// spirit as rustc's `E0xxx` codes. Downstream tools can match on this
+/// instead of parsing the rendered message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiagnosticCode {
+    TableNameInvalid,
+    TableConstructorExportSizeInvalid,
+    Pattern,
+    Disassembly,
+    Display,
+    Execution,
+}
+impl DiagnosticCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TableNameInvalid => "E0101",
+            Self::TableConstructorExportSizeInvalid => "E0102",
+            Self::Pattern => "E0103",
+            Self::Disassembly => "E0104",
+            Self::Display => "E0105",
+            Self::Execution => "E0106",
+        }
+    }
+}
+impl std::fmt::Display for DiagnosticCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A rustc-style diagnostic: a primary span, an ordered list of secondary
+/// labeled spans (e.g. pointing at a conflicting constructor), and an
+/// optional help message, all tagged with a stable [`DiagnosticCode`].
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub code: DiagnosticCode,
+    pub primary: InputSource,
+    pub labels: Vec<(InputSource, String)>,
+    pub help: Option<String>,
+}
+impl Diagnostic {
+    pub fn new(code: DiagnosticCode, primary: InputSource) -> Self {
+        Self {
+            code,
+            primary,
+            labels: Vec::new(),
+            help: None,
+        }
+    }
+    pub fn with_label(mut self, src: InputSource, msg: impl Into<String>) -> Self {
+        self.labels.push((src, msg.into()));
+        self
+    }
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+}
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "[{}] at {}", self.code, self.primary)?;
+        for (src, msg) in &self.labels {
+            writeln!(f, "  {src}: {msg}")?;
+        }
+        if let Some(help) = &self.help {
+            writeln!(f, "help: {help}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Implemented by an error type so a wrapper can reuse its backtrace
+/// instead of capturing a new, less useful one of its own. `TableError`
+/// and `TableErrorSub` implement it below. `TableError` keeps the backtrace
+/// it was built with and returns that same one on every call.
+/// `TableErrorSub` keeps none: each call captures a new backtrace, so a
+/// `TableError` built by [`ToTableError::to_table`] or
+/// [`TableErrorSub::to_table`] holds a backtrace of the point where it was
+/// wrapped, not of where the `TableErrorSub` was produced.
+///
+/// NOT currently implemented for `PatternError`, `DisassemblyError`,
+/// `DisplayError` or `ExecutionError`: those live in
+/// `pattern.rs`/`disassembly.rs`/`display.rs`/`execution.rs`, none of
+/// which are part of this checkout. This means a `TableErrorSub` wrapping
+/// one of them still captures its own, separate backtrace at the wrap
+/// site rather than reusing wherever the original error actually
+/// originated — see `TableErrorSub::backtrace` below. Making that work
+/// end-to-end is blocked on those modules implementing this trait; it
+/// isn't attempted here.
+pub trait HasBacktrace {
+    /// The backtrace this error carries: for `TableError`, the one captured
+    /// when it was built; for `TableErrorSub`, a new one captured by this
+    /// call. Empty unless `RUST_BACKTRACE`/`RUST_LIB_BACKTRACE` is set, per
+    /// [`Backtrace::capture`]'s own gating.
+    fn backtrace(&self) -> Rc<Backtrace>;
+}
 
 #[derive(Clone, Debug, Error)]
-#[error("at {table_pos}\n{sub}")]
+#[error("{diagnostic}{sub}")]
 pub struct TableError {
-    pub table_pos: InputSource,
     pub sub: TableErrorSub,
+    pub diagnostic: Diagnostic,
+    backtrace: Rc<Backtrace>,
+}
+impl TableError {
+    /// The primary source location of this error.
+    ///
+    /// Breaking change: before the `Diagnostic` rework, `table_pos` was a
+    /// public `InputSource` field (so callers read it as `err.table_pos` and
+    /// could move or pattern-match it out directly). It's now this accessor
+    /// returning `&InputSource` — the position lives inside `diagnostic`,
+    /// which also carries the labels and help text matching it. Any
+    /// out-of-checkout caller doing `err.table_pos` or destructuring
+    /// `TableError { table_pos, .. }` needs updating to `err.table_pos()`
+    /// (and a `.clone()` if it needs an owned `InputSource`).
+    pub fn table_pos(&self) -> &InputSource {
+        &self.diagnostic.primary
+    }
+}
+impl HasBacktrace for TableError {
+    fn backtrace(&self) -> Rc<Backtrace> {
+        Rc::clone(&self.backtrace)
+    }
 }
 
 pub trait ToTableError<X> {
@@ -33,9 +153,15 @@ where
     T: Into<TableErrorSub>,
 {
     fn to_table(self, table_pos: InputSource) -> Result<X, TableError> {
-        self.map_err(|e| TableError {
-            table_pos,
-            sub: e.into(),
+        self.map_err(|e| {
+            let sub = e.into();
+            let diagnostic = Diagnostic::new(sub.code(), table_pos);
+            let backtrace = sub.backtrace();
+            TableError {
+                sub,
+                diagnostic,
+                backtrace,
+            }
         })
     }
 }
@@ -58,19 +184,44 @@ pub enum TableErrorSub {
     Execution(ExecutionError),
 }
 impl TableErrorSub {
+    pub fn code(&self) -> DiagnosticCode {
+        match self {
+            Self::TableNameInvalid => DiagnosticCode::TableNameInvalid,
+            Self::TableConstructorExportSizeInvalid => {
+                DiagnosticCode::TableConstructorExportSizeInvalid
+            }
+            Self::Pattern(_) => DiagnosticCode::Pattern,
+            Self::Disassembly(_) => DiagnosticCode::Disassembly,
+            Self::Display(_) => DiagnosticCode::Display,
+            Self::Execution(_) => DiagnosticCode::Execution,
+        }
+    }
     pub fn to_table(self, table_pos: InputSource) -> TableError {
+        let diagnostic = Diagnostic::new(self.code(), table_pos);
+        let backtrace = self.backtrace();
         TableError {
-            table_pos,
             sub: self,
+            diagnostic,
+            backtrace,
         }
     }
 }
+impl HasBacktrace for TableErrorSub {
+    fn backtrace(&self) -> Rc<Backtrace> {
+        // Blocked, not deferred: properly preserving the innermost
+        // backtrace for the `Pattern`/`Disassembly`/`Display`/`Execution`
+        // variants needs those error types to implement `HasBacktrace`
+        // themselves, in modules that aren't part of this checkout (see
+        // the trait doc above). Every variant captures fresh here instead.
+        Rc::new(Backtrace::capture())
+    }
+}
 from_error!(TableErrorSub, DisassemblyError, Disassembly);
 from_error!(TableErrorSub, PatternError, Pattern);
 from_error!(TableErrorSub, DisplayError, Display);
 from_error!(TableErrorSub, ExecutionError, Execution);
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, serde::Serialize, serde::Deserialize)]
 pub enum ExecutionExport {
     //don't return
     #[default]
@@ -96,6 +247,16 @@ impl ExecutionExport {
             | Self::Multiple(len) => Some(*len),
         }
     }
+
+    /// Whether a constructor exporting `self` can coexist, in the same
+    /// table, with one exporting `other`. `None` (no export) is
+    /// compatible with anything; otherwise the two must agree on size.
+    pub fn is_compatible_with(&self, other: &Self) -> bool {
+        match (self.len(), other.len()) {
+            (None, _) | (_, None) => true,
+            (a, b) => a == b,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -125,6 +286,81 @@ impl Table {
             export: RefCell::default(),
         }
     }
+
+    /// Converts a parsed constructor and adds it to this table, labeling any
+    /// resulting error with context `Constructor::try_from` alone doesn't
+    /// have: a `Pattern` error is labeled with the most recently inserted
+    /// constructor of this table (which sibling, if any, the pattern
+    /// actually overlaps is not determined), and an export-size mismatch
+    /// with the first constructor that established the table's export size.
+    ///
+    /// Nothing in this checkout calls this yet: the parser driver that
+    /// builds a `Table`'s constructors lives in `inner`/the SLEIGH grammar
+    /// front end, neither of which is part of this checkout, and it still
+    /// calls `Constructor::try_from` directly. This is the entry point that
+    /// driver should switch to for the labeling above; until it does, treat
+    /// this as prepared but not wired in, not as already integrated.
+    pub fn insert_constructor(&self, value: inner::Constructor) -> Result<(), TableError> {
+        let src = value.src.clone();
+        let constructor = {
+            let converted = self.constructors.borrow();
+            Constructor::try_from(value).map_err(|mut err| {
+                if matches!(err.sub, TableErrorSub::Pattern(_)) {
+                    if let Some(other) = converted.last() {
+                        err.diagnostic = err.diagnostic.with_label(
+                            other.src.clone(),
+                            format!(
+                                "conflicts with this constructor of table `{}`",
+                                self.name
+                            ),
+                        );
+                    }
+                }
+                err
+            })?
+        };
+        let export = constructor
+            .execution
+            .as_ref()
+            .map(Execution::export)
+            .unwrap_or_default();
+        self.check_export_size(export, src)?;
+        self.constructors.borrow_mut().push(constructor);
+        Ok(())
+    }
+
+    /// Checks `export` against the export kind already established by this
+    /// table's earlier constructors, labeling the error with the first
+    /// constructor that established it if they're incompatible. Used by
+    /// [`Table::insert_constructor`].
+    fn check_export_size(
+        &self,
+        export: ExecutionExport,
+        src: InputSource,
+    ) -> Result<(), TableError> {
+        let mut current = self.export.borrow_mut();
+        if !current.is_compatible_with(&export) {
+            let mut err = TableErrorSub::TableConstructorExportSizeInvalid.to_table(src);
+            // The first constructor whose own export has a size is the one
+            // that set `current` below; earlier ones exporting nothing
+            // left it unset.
+            let established = self.constructors.borrow().iter().find_map(|c| {
+                let export = c.execution.as_ref().map(Execution::export).unwrap_or_default();
+                export.len().map(|_| c.src.clone())
+            });
+            if let Some(first) = established {
+                err.diagnostic = err.diagnostic.with_label(
+                    first,
+                    format!("export size of `{}` established here", self.name),
+                );
+            }
+            return Err(err);
+        }
+        if current.len().is_none() {
+            *current = export;
+        }
+        Ok(())
+    }
 }
 
 impl<'a> TryFrom<inner::Constructor> for Constructor {
@@ -145,3 +381,45 @@ impl<'a> TryFrom<inner::Constructor> for Constructor {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostic_code_is_stable_and_displays_as_its_code() {
+        assert_eq!(DiagnosticCode::TableNameInvalid.as_str(), "E0101");
+        assert_eq!(DiagnosticCode::Execution.as_str(), "E0106");
+        assert_eq!(DiagnosticCode::Pattern.to_string(), "E0103");
+    }
+
+    #[test]
+    fn none_export_is_compatible_with_anything() {
+        let value = ExecutionExport::Value(NonZeroTypeU::new(4).unwrap());
+        assert!(ExecutionExport::None.is_compatible_with(&value));
+        assert!(value.is_compatible_with(&ExecutionExport::None));
+        assert!(ExecutionExport::None.is_compatible_with(&ExecutionExport::None));
+    }
+
+    #[test]
+    fn same_size_exports_are_compatible_regardless_of_kind() {
+        let len = NonZeroTypeU::new(4).unwrap();
+        assert!(ExecutionExport::Value(len).is_compatible_with(&ExecutionExport::Const(len)));
+    }
+
+    #[test]
+    fn different_size_exports_are_incompatible() {
+        let a = ExecutionExport::Value(NonZeroTypeU::new(4).unwrap());
+        let b = ExecutionExport::Value(NonZeroTypeU::new(8).unwrap());
+        assert!(!a.is_compatible_with(&b));
+    }
+
+    #[test]
+    fn unit_error_variants_are_constructible_and_capture_a_backtrace() {
+        // Exercises `HasBacktrace::backtrace` on the unit variants;
+        // whether the result is non-empty depends on `RUST_BACKTRACE`,
+        // which this test doesn't control.
+        let _ = TableErrorSub::TableNameInvalid.backtrace();
+        let _ = TableErrorSub::TableConstructorExportSizeInvalid.backtrace();
+    }
+}
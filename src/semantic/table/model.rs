@@ -0,0 +1,204 @@
+//! On-disk, serializable representation of compiled `Table`s.
+//!
+//! `Table` itself can't derive `Serialize`/`Deserialize`: it holds
+//! `RefCell<Vec<Constructor>>`/`RefCell<ExecutionExport>`, and its `name` is
+//! an `Rc<str>` that's also held (by the same pointer) by every other table
+//! and constructor that refers to it. Naively serializing those `Rc`s would
+//! duplicate the name at every use site and lose the sharing on the way
+//! back in. `Model` is a flat arena instead: every table name is interned
+//! once into `names`, and a table refers to itself (and, once wired up,
+//! would refer to any other table it names) by a [`TableRef`] index into
+//! that arena.
+//!
+//! Current scope: only a table's `name` and its overall [`ExecutionExport`]
+//! are cached here — `Constructor` itself (its `pattern`/`display`/
+//! `disassembly`/`execution` fields) is deliberately NOT serialized by this
+//! module yet. Those field types live in `pattern.rs`/`display.rs`/
+//! `disassembly.rs`/`execution.rs`, none of which are part of this
+//! checkout, and SLEIGH patterns can reference other tables (e.g. a
+//! sub-table match) — if any of those fields hold their own `Rc<Table>`
+//! back-reference, blanket-deriving `Serialize` for `Constructor` would try
+//! to serialize the referenced `Table` structurally instead of through this
+//! arena, which is exactly the duplicated/unshareable representation this
+//! request exists to avoid. Serializing `Constructor` safely needs those
+//! sibling modules to route any such reference through a [`TableRef`]
+//! obtained from the same [`Interner`] used below; until they do, this
+//! module caches table metadata only, not the constructor list, rather
+//! than shipping a derive that's silently unsound for the real types.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use serde::{Deserialize, Serialize};
+
+use super::{ExecutionExport, Table};
+
+/// Bumped whenever the on-disk shape of [`Model`] changes. [`tables_from_bytes`]
+/// rejects a cache written by a different version instead of guessing at
+/// how to read it.
+pub const MODEL_VERSION: u32 = 1;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ModelError {
+    #[error("cached table model was built by format version {found}, expected {MODEL_VERSION}")]
+    VersionMismatch { found: u32 },
+    #[error("failed to decode cached table model: {0}")]
+    Decode(#[from] bincode::Error),
+}
+
+/// Index into a [`Model`]'s interned table names. Any table-to-table
+/// reference in the model (currently just a `TableModel`'s own name)
+/// should be one of these rather than a structurally-serialized `Table`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableRef(pub u32);
+
+#[derive(Serialize, Deserialize)]
+struct Model {
+    version: u32,
+    /// Interned table names. A table refers to itself, and would refer to
+    /// any table it names, by `TableRef` into this arena rather than by
+    /// `Rc`.
+    names: Vec<String>,
+    tables: Vec<TableModel>,
+}
+
+/// A single table's cached metadata. Does not include its constructors;
+/// see the module doc for why.
+#[derive(Serialize, Deserialize)]
+struct TableModel {
+    name: TableRef,
+    export: ExecutionExport,
+}
+
+/// Interns `Rc<str>` table names into a flat arena, so the same name is
+/// stored once no matter how many tables or constructors refer to it.
+/// Exposed so sibling modules (e.g. wherever `Pattern` ends up needing to
+/// serialize a reference to another table) can intern into the same arena
+/// instead of inventing their own.
+pub struct Interner {
+    names: Vec<String>,
+    index_of: HashMap<String, TableRef>,
+}
+impl Interner {
+    pub fn new() -> Self {
+        Self {
+            names: Vec::new(),
+            index_of: HashMap::new(),
+        }
+    }
+    pub fn intern(&mut self, name: &Rc<str>) -> TableRef {
+        if let Some(&index) = self.index_of.get(name.as_ref()) {
+            return index;
+        }
+        let index = TableRef(self.names.len() as u32);
+        self.names.push(name.to_string());
+        self.index_of.insert(name.to_string(), index);
+        index
+    }
+}
+impl Default for Interner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Serializes a set of tables' metadata (name and export kind, not their
+/// constructors — see the module doc) into the crate's compact binary
+/// cache format. Table names shared between entries are interned once.
+pub fn tables_to_bytes(tables: &[Rc<Table>]) -> Vec<u8> {
+    let mut interner = Interner::new();
+    let table_models = tables
+        .iter()
+        .map(|table| TableModel {
+            name: interner.intern(&table.name),
+            export: *table.export.borrow(),
+        })
+        .collect();
+    let model = Model {
+        version: MODEL_VERSION,
+        names: interner.names,
+        tables: table_models,
+    };
+    bincode::serialize(&model).expect("Table model serialization is infallible")
+}
+
+/// Reloads table metadata previously written with [`tables_to_bytes`]. The
+/// reloaded tables have an empty constructor list; see the module doc.
+pub fn tables_from_bytes(bytes: &[u8]) -> Result<Vec<Rc<Table>>, ModelError> {
+    let model: Model = bincode::deserialize(bytes)?;
+    if model.version != MODEL_VERSION {
+        return Err(ModelError::VersionMismatch {
+            found: model.version,
+        });
+    }
+    Ok(model
+        .tables
+        .into_iter()
+        .map(|table_model| {
+            let name = Rc::from(model.names[table_model.name.0 as usize].as_str());
+            let table = Table::new_empty(name);
+            *table.export.borrow_mut() = table_model.export;
+            Rc::new(table)
+        })
+        .collect())
+}
+
+impl Table {
+    /// Serializes this table's metadata alone into the crate's compact
+    /// binary cache format. Use [`tables_to_bytes`] to serialize several
+    /// tables (e.g. a whole compiled model) together with their names
+    /// interned once.
+    pub fn to_model_bytes(&self) -> Vec<u8> {
+        tables_to_bytes(&[Rc::new(self.clone())])
+    }
+
+    /// Reloads a table's metadata previously written with
+    /// [`Table::to_model_bytes`].
+    pub fn from_model_bytes(bytes: &[u8]) -> Result<Self, ModelError> {
+        let table = tables_from_bytes(bytes)?
+            .into_iter()
+            .next()
+            .expect("to_model_bytes always writes exactly one table");
+        Ok((*table).clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_a_single_empty_table() {
+        let table = Table::new_empty(Rc::from("instruction"));
+        let bytes = table.to_model_bytes();
+        let reloaded = Table::from_model_bytes(&bytes).unwrap();
+        assert_eq!(reloaded.name.as_ref(), "instruction");
+        assert!(reloaded.constructors.borrow().is_empty());
+    }
+
+    #[test]
+    fn interns_shared_names_once_across_several_tables() {
+        let tables = vec![
+            Rc::new(Table::new_empty(Rc::from("instruction"))),
+            Rc::new(Table::new_empty(Rc::from("reg"))),
+            Rc::new(Table::new_empty(Rc::from("reg"))),
+        ];
+        let bytes = tables_to_bytes(&tables);
+        let model: Model = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(model.names.len(), 2);
+        assert_eq!(model.tables[1].name, model.tables[2].name);
+    }
+
+    #[test]
+    fn rejects_a_cache_from_a_different_format_version() {
+        let mut model_bytes = Table::new_empty(Rc::from("instruction")).to_model_bytes();
+        // Version is the first serialized field (a little-endian u32 under
+        // bincode's default config); corrupt it to simulate a stale cache.
+        model_bytes[0] = model_bytes[0].wrapping_add(1);
+        assert!(matches!(
+            Table::from_model_bytes(&model_bytes),
+            Err(ModelError::VersionMismatch { .. })
+        ));
+    }
+}